@@ -1,13 +1,22 @@
-pub use git2::{DiffFormat, DiffOptions, Repository, Signature, Sort, Status, StatusOptions};
-use log::{debug, info};
+use git2::{
+    build::{CheckoutBuilder, TreeUpdateBuilder},
+    Branch, ErrorCode, FileMode, IndexTime, ObjectType, Rebase, ResetType, TreeWalkMode,
+    TreeWalkResult,
+};
+pub use git2::{
+    BranchType, Delta, DiffFormat, DiffOptions, IndexEntry, Oid, Repository, RepositoryState,
+    Signature, Sort, StashFlags, Status, StatusOptions,
+};
+use log::{debug, info, warn};
 
 use std::{
+    fmt,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    time::SystemTime,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 #[derive(Debug, Clone)]
 pub struct FileStatus {
@@ -15,6 +24,148 @@ pub struct FileStatus {
     pub status: Status,
 }
 
+/// Which two states of a file a diff compares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiffMode {
+    /// HEAD against the working directory, like `git diff HEAD`
+    #[default]
+    HeadToWorkdir,
+    /// Index against the working directory, like `git diff`
+    IndexToWorkdir,
+    /// HEAD against the index, like `git diff --cached`
+    HeadToIndex,
+}
+
+/// A file as it will end up in the next commit, with its diff stats against HEAD.
+#[derive(Debug, Clone)]
+pub struct StagedFile {
+    pub path: PathBuf,
+    pub delta: Delta,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// A copy of the index, taken with `RepoCache::snapshot_index`
+/// and put back with `RepoCache::restore_index`.
+#[derive(Debug)]
+pub struct IndexSnapshot {
+    entries: Vec<IndexEntry>,
+}
+
+/// An entry of the stash, like a line of `git stash list`.
+#[derive(Debug, Clone)]
+pub struct StashItem {
+    pub index: usize,
+    pub message: String,
+    pub commit: String,
+}
+
+/// A multi-step operation that the repository is in the middle of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+    ApplyMailbox,
+}
+
+impl Operation {
+    /// The operation a repository state stands for, `None` if it is clean
+    fn from_state(state: RepositoryState) -> Option<Self> {
+        let operation = match state {
+            RepositoryState::Clean => return None,
+            RepositoryState::Merge => Operation::Merge,
+            RepositoryState::Revert | RepositoryState::RevertSequence => Operation::Revert,
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                Operation::CherryPick
+            }
+            RepositoryState::Bisect => Operation::Bisect,
+            RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge
+            | RepositoryState::ApplyMailboxOrRebase => Operation::Rebase,
+            RepositoryState::ApplyMailbox => Operation::ApplyMailbox,
+        };
+        Some(operation)
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Merge => "merge",
+            Operation::Rebase => "rebase",
+            Operation::CherryPick => "cherry-pick",
+            Operation::Revert => "revert",
+            Operation::Bisect => "bisect",
+            Operation::ApplyMailbox => "am",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// An operation in progress, with what is known about it.
+/// Fields that don't apply to the operation are empty.
+#[derive(Debug, Clone)]
+pub struct OperationState {
+    pub operation: Operation,
+    /// The commits being merged, cherry-picked or reverted. For a rebase, the original HEAD.
+    pub heads: Vec<String>,
+    /// For a rebase, the branch that is being rebased
+    pub branch: Option<String>,
+    /// For a rebase, the commit it is being rebased onto
+    pub onto: Option<String>,
+    /// The prepared commit message (MERGE_MSG)
+    pub message: Option<String>,
+    /// For a rebase, the current step and the total number of steps
+    pub progress: Option<(usize, usize)>,
+    /// Whether this cherry-pick or revert is one of several driven by git's sequencer.
+    /// Those can only be continued or aborted with git itself.
+    pub sequence: bool,
+    /// Whether this rebase was started by a tool libgit2 can't follow, like the git CLI.
+    /// Those can only be aborted from here.
+    pub external: bool,
+}
+
+/// A local branch and how far it is from its upstream, like a line of `git branch -vv`.
+#[derive(Debug, Clone)]
+pub struct BranchItem {
+    pub name: String,
+    /// Whether this branch is checked out
+    pub is_head: bool,
+    /// The upstream branch, e.g. `origin/main`
+    pub upstream: Option<String>,
+    /// Commits on this branch that are not on its upstream (need pushing)
+    pub ahead: usize,
+    /// Commits on the upstream that are not on this branch (need pulling)
+    pub behind: usize,
+}
+
+/// A file or directory in the tree of a commit.
+#[derive(Debug, Clone)]
+pub struct TreeItem {
+    /// Path relative to the repository root
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// The state of a repository at a glance, for watching several at once.
+#[derive(Debug, Clone)]
+pub struct RepoSummary {
+    pub root: PathBuf,
+    /// The checked out branch, `None` for a detached HEAD
+    pub branch: Option<String>,
+    /// Number of files with staged, unstaged or untracked changes
+    pub dirty_files: usize,
+    pub ahead: usize,
+    pub behind: usize,
+    /// Why the repository could not be read, if it couldn't
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct LogItem {
     pub name: String,
@@ -24,12 +175,22 @@ pub struct LogItem {
     pub message: String,
 }
 
+/// A handle to a repository and what was last read from it. Clones share the same state.
+#[derive(Clone)]
 pub struct RepoCache {
     pub repo: Arc<Mutex<Repository>>,
     pub statuses: Arc<Mutex<Vec<FileStatus>>>,
     pub log: Arc<Mutex<Vec<LogItem>>>,
+    pub staged: Arc<Mutex<Vec<StagedFile>>>,
+    pub stashes: Arc<Mutex<Vec<StashItem>>>,
+    pub operation: Arc<Mutex<Option<OperationState>>>,
+    pub branches: Arc<Mutex<Vec<BranchItem>>>,
     pub local_refresh: Arc<Mutex<Option<SystemTime>>>,
     pub remote_refresh: Arc<Mutex<Option<SystemTime>>>,
+    /// How many commits `refresh` loads into the log
+    pub log_depth: Arc<Mutex<usize>>,
+    /// Held while refreshing, so an older refresh can't overwrite the results of a newer one
+    refresh_lock: Arc<Mutex<()>>,
 }
 
 impl RepoCache {
@@ -53,8 +214,35 @@ impl RepoCache {
         (*self.log.lock().unwrap()).clone()
     }
 
+    pub fn get_staged(&self) -> Vec<StagedFile> {
+        (*self.staged.lock().unwrap()).clone()
+    }
+
+    pub fn get_stashes(&self) -> Vec<StashItem> {
+        (*self.stashes.lock().unwrap()).clone()
+    }
+
+    pub fn get_operation(&self) -> Option<OperationState> {
+        (*self.operation.lock().unwrap()).clone()
+    }
+
+    pub fn get_branches(&self) -> Vec<BranchItem> {
+        (*self.branches.lock().unwrap()).clone()
+    }
+
+    pub fn get_log_depth(&self) -> usize {
+        *self.log_depth.lock().unwrap()
+    }
+
+    pub fn set_log_depth(&self, depth: usize) {
+        *self.log_depth.lock().unwrap() = depth;
+    }
+
+    /// The working directory, or the git directory of a bare repository.
+    /// Every worktree of a repository has its own root.
     pub fn get_root(&self) -> PathBuf {
-        self.repo.lock().unwrap().commondir().to_path_buf()
+        let repo = self.repo.lock().unwrap();
+        repo.workdir().unwrap_or(repo.path()).to_path_buf()
     }
 
     pub fn open(path: &Path) -> Result<Self> {
@@ -63,8 +251,14 @@ impl RepoCache {
             repo: Arc::new(Mutex::new(repo)),
             statuses: Arc::new(Mutex::new(vec![])),
             log: Arc::new(Mutex::new(vec![])),
+            staged: Arc::new(Mutex::new(vec![])),
+            stashes: Arc::new(Mutex::new(vec![])),
+            operation: Arc::new(Mutex::new(None)),
+            branches: Arc::new(Mutex::new(vec![])),
             local_refresh: Arc::new(Mutex::new(None)),
             remote_refresh: Arc::new(Mutex::new(None)),
+            log_depth: Arc::new(Mutex::new(10)),
+            refresh_lock: Arc::new(Mutex::new(())),
         })
     }
 
@@ -84,6 +278,174 @@ impl RepoCache {
         Ok(())
     }
 
+    /// Lists all local branches with their ahead/behind counts against their upstream.
+    /// Counts are against the last fetched state of the upstream.
+    pub fn branches(&self) -> Result<Vec<BranchItem>> {
+        let repo = self.repo.lock().unwrap();
+
+        let mut branches = vec![];
+        for branch in repo.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            let name = branch.name()?.unwrap_or("<invalid utf-8>").to_string();
+
+            let mut item = BranchItem {
+                name,
+                is_head: branch.is_head(),
+                upstream: None,
+                ahead: 0,
+                behind: 0,
+            };
+
+            // A broken upstream only costs this branch its ahead/behind, not the whole list
+            if let Ok(upstream) = branch.upstream() {
+                item.upstream = upstream.name().ok().flatten().map(str::to_string);
+                if let (Some(local), Some(remote)) =
+                    (branch.get().target(), upstream.get().target())
+                {
+                    match repo.graph_ahead_behind(local, remote) {
+                        Ok(counts) => (item.ahead, item.behind) = counts,
+                        Err(e) => warn!("Can't compare {} with its upstream: {e}", item.name),
+                    }
+                }
+            }
+
+            branches.push(item);
+        }
+
+        Ok(branches)
+    }
+
+    /// Lists every file and directory of a revision, like `git ls-tree -r -t <revision>`.
+    /// Directories come right before their contents.
+    pub fn walk_tree(&self, revision: &str) -> Result<Vec<TreeItem>> {
+        let repo = self.repo.lock().unwrap();
+        let tree = repo.revparse_single(revision)?.peel_to_tree()?;
+
+        let mut items = vec![];
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if let Some(name) = entry.name() {
+                items.push(TreeItem {
+                    path: Path::new(dir).join(name),
+                    is_dir: entry.kind() == Some(ObjectType::Tree),
+                });
+            }
+            TreeWalkResult::Ok
+        })?;
+
+        Ok(items)
+    }
+
+    /// Returns the content of a file as of a revision, like `git show <revision>:<path>`.
+    /// The working directory is not touched.
+    pub fn show_file(&self, revision: &str, path: &Path) -> Result<String> {
+        let repo = self.repo.lock().unwrap();
+        let tree = repo.revparse_single(revision)?.peel_to_tree()?;
+        let blob = tree.get_path(path)?.to_object(&repo)?.peel_to_blob()?;
+
+        if blob.is_binary() {
+            anyhow::bail!("{} is a binary file", path.display());
+        }
+        Ok(String::from_utf8_lossy(blob.content()).to_string())
+    }
+
+    /// Sums up the repository: checked out branch, number of changed files and ahead/behind.
+    /// Unlike `refresh`, this runs on the calling thread and caches nothing.
+    pub fn summary(&self) -> Result<RepoSummary> {
+        let repo = self.repo.lock().unwrap();
+        let mut status_opts = StatusOptions::new();
+        status_opts
+            .include_untracked(true)
+            .recurse_untracked_dirs(true);
+
+        let mut summary = RepoSummary {
+            root: repo.workdir().unwrap_or(repo.path()).to_path_buf(),
+            branch: None,
+            dirty_files: repo.statuses(Some(&mut status_opts))?.len(),
+            ahead: 0,
+            behind: 0,
+            error: None,
+        };
+
+        // A detached or unborn HEAD has no branch to report
+        let head = match repo.head() {
+            Ok(head) if head.is_branch() => Branch::wrap(head),
+            _ => return Ok(summary),
+        };
+        summary.branch = head.name()?.map(str::to_string);
+        if let Ok(upstream) = head.upstream() {
+            if let (Some(local), Some(remote)) = (head.get().target(), upstream.get().target()) {
+                (summary.ahead, summary.behind) = repo.graph_ahead_behind(local, remote)?;
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Takes a copy of the index, so staging changes can be undone later.
+    pub fn snapshot_index(&self) -> Result<IndexSnapshot> {
+        let index = self.repo.lock().unwrap().index()?;
+        Ok(IndexSnapshot {
+            entries: index.iter().collect(),
+        })
+    }
+
+    /// Replaces the index with a snapshot. The working directory is left untouched.
+    pub fn restore_index(&self, snapshot: &IndexSnapshot) -> Result<()> {
+        let mut index = self.repo.lock().unwrap().index()?;
+        index.clear()?;
+        for entry in &snapshot.entries {
+            index.add(entry)?;
+        }
+        index.write()?;
+        self.refresh()?;
+        Ok(())
+    }
+
+    /// Stashes all changes, like `git stash push --include-untracked`.
+    pub fn stash(&self) -> Result<()> {
+        self.stash_paths(&[])
+    }
+
+    /// Stashes only the changes to `paths`, like `git stash push --include-untracked -- <paths>`.
+    /// All other changes stay where they are. An empty list stashes everything.
+    pub fn stash_paths(&self, paths: &[PathBuf]) -> Result<()> {
+        {
+            let mut repo = self.repo.lock().unwrap();
+            let stash_id = if paths.is_empty() {
+                let sig = repo.signature()?;
+                repo.stash_save2(&sig, None, Some(StashFlags::INCLUDE_UNTRACKED))?
+            } else {
+                // libgit2's own pathspec stashing also resets files outside the pathspec,
+                // so the stash commits are built by hand, the same way git does it.
+                stash_paths(&repo, paths)?
+            };
+            debug!("Stashed {} paths as {}", paths.len(), stash_id);
+        }
+        self.refresh()?;
+        Ok(())
+    }
+
+    /// Applies a stash and removes it, like `git stash pop stash@{index}`.
+    pub fn stash_pop(&self, index: usize) -> Result<()> {
+        self.repo.lock().unwrap().stash_pop(index, None)?;
+        self.refresh()?;
+        Ok(())
+    }
+
+    pub fn stash_list(&self) -> Result<Vec<StashItem>> {
+        let mut repo = self.repo.lock().unwrap();
+        let mut stashes = vec![];
+        repo.stash_foreach(|index, message, oid| {
+            stashes.push(StashItem {
+                index,
+                message: message.to_string(),
+                commit: oid.to_string(),
+            });
+            true
+        })?;
+        Ok(stashes)
+    }
+
     pub fn refresh_log(&self, max_commits: usize) -> Result<Vec<LogItem>> {
         let repo = self.repo.lock().unwrap();
 
@@ -141,9 +503,173 @@ impl RepoCache {
         Ok(log)
     }
 
+    /// Reports a merge, rebase, cherry-pick, revert or bisect in progress, or `None` if the repository is clean.
+    pub fn operation_state(&self) -> Result<Option<OperationState>> {
+        let mut repo = self.repo.lock().unwrap();
+
+        let Some(operation) = Operation::from_state(repo.state()) else {
+            return Ok(None);
+        };
+
+        let mut state = OperationState {
+            operation,
+            heads: vec![],
+            branch: None,
+            onto: None,
+            message: repo.message().ok(),
+            progress: None,
+            sequence: matches!(
+                repo.state(),
+                RepositoryState::CherryPickSequence | RepositoryState::RevertSequence
+            ),
+            external: false,
+        };
+
+        match operation {
+            Operation::Merge => {
+                repo.mergehead_foreach(|oid| {
+                    state.heads.push(oid.to_string());
+                    true
+                })?;
+            }
+            Operation::CherryPick | Operation::Revert => {
+                let head_name = if operation == Operation::CherryPick {
+                    "CHERRY_PICK_HEAD"
+                } else {
+                    "REVERT_HEAD"
+                };
+                if let Ok(head) = repo.revparse_single(head_name) {
+                    state.heads.push(head.id().to_string());
+                }
+            }
+            Operation::Rebase => {
+                // Depending on the backend, the rebase keeps its state in one of these
+                let (dir, step, total) = if repo.path().join("rebase-merge").is_dir() {
+                    (repo.path().join("rebase-merge"), "msgnum", "end")
+                } else {
+                    (repo.path().join("rebase-apply"), "next", "last")
+                };
+                let read = |name: &str| {
+                    std::fs::read_to_string(dir.join(name))
+                        .ok()
+                        .map(|content| content.trim().to_string())
+                };
+
+                // A detached HEAD is recorded as "detached HEAD"
+                state.branch = read("head-name")
+                    .and_then(|name| name.strip_prefix("refs/heads/").map(str::to_string));
+                state.heads.extend(read("orig-head"));
+                state.onto = read("onto");
+                state.progress = read(step)
+                    .and_then(|step| step.parse().ok())
+                    .zip(read(total).and_then(|total| total.parse().ok()));
+                state.external = repo.open_rebase(None).is_err();
+            }
+            Operation::Bisect | Operation::ApplyMailbox => {}
+        }
+
+        Ok(Some(state))
+    }
+
+    /// Concludes the operation in progress once all conflicts are resolved and staged,
+    /// like `git merge --continue` or `git rebase --continue`.
+    pub fn continue_operation(&self) -> Result<()> {
+        let state = self
+            .operation_state()?
+            .context("There is no operation in progress")?;
+        check_sequence(&state)?;
+        {
+            let repo = self.repo.lock().unwrap();
+            if repo.index()?.has_conflicts() {
+                anyhow::bail!("Resolve and stage all conflicts first");
+            }
+            match state.operation {
+                Operation::Merge | Operation::CherryPick | Operation::Revert => {
+                    commit_operation(&repo, &state)?;
+                    repo.cleanup_state()?;
+                }
+                Operation::Rebase => {
+                    let mut rebase = open_rebase(&repo)?;
+                    continue_rebase(&repo, &mut rebase, true)?;
+                }
+                operation => anyhow::bail!("Can't continue a {operation} from here"),
+            }
+        }
+        self.refresh()?;
+        Ok(())
+    }
+
+    /// Drops the current step of the operation in progress, like `git rebase --skip`.
+    /// The changes of that step are discarded, other uncommitted changes are kept.
+    pub fn skip_operation(&self) -> Result<()> {
+        let state = self
+            .operation_state()?
+            .context("There is no operation in progress")?;
+        check_sequence(&state)?;
+        {
+            let repo = self.repo.lock().unwrap();
+            match state.operation {
+                Operation::CherryPick | Operation::Revert => {
+                    reset_operation_paths(&repo)?;
+                    repo.cleanup_state()?;
+                }
+                Operation::Rebase => {
+                    let mut rebase = open_rebase(&repo)?;
+                    reset_operation_paths(&repo)?;
+                    continue_rebase(&repo, &mut rebase, false)?;
+                }
+                operation => anyhow::bail!("Can't skip a {operation}"),
+            }
+        }
+        self.refresh()?;
+        Ok(())
+    }
+
+    /// Abandons the operation in progress and goes back to where it started,
+    /// like `git merge --abort` or `git rebase --abort`. The changes the operation brought in
+    /// are discarded, other uncommitted changes are kept unless a rebase has to move HEAD back.
+    pub fn abort_operation(&self) -> Result<()> {
+        let state = self
+            .operation_state()?
+            .context("There is no operation in progress")?;
+        check_sequence(&state)?;
+        {
+            let repo = self.repo.lock().unwrap();
+            match state.operation {
+                Operation::Merge | Operation::CherryPick | Operation::Revert => {
+                    reset_operation_paths(&repo)?;
+                    repo.cleanup_state()?;
+                }
+                Operation::Rebase => match repo.open_rebase(None) {
+                    Ok(mut rebase) => rebase.abort()?,
+                    // Not a rebase libgit2 can read, so go back to the original branch by hand
+                    Err(_) => {
+                        let orig_head = state.heads.first().context("Unknown original HEAD")?;
+                        let orig_head = repo.find_commit(Oid::from_str(orig_head)?)?;
+                        match &state.branch {
+                            Some(branch) => repo.set_head(&format!("refs/heads/{branch}"))?,
+                            None => repo.set_head_detached(orig_head.id())?,
+                        }
+                        repo.reset(orig_head.as_object(), ResetType::Hard, None)?;
+                        repo.cleanup_state()?;
+                    }
+                },
+                operation => anyhow::bail!("Can't abort a {operation} from here"),
+            }
+        }
+        self.refresh()?;
+        Ok(())
+    }
+
     pub fn commit(&self) -> Result<()> {
         let repo = self.repo.lock().unwrap();
 
+        // Committing while bisecting is fine, everything else has its own way to conclude
+        match Operation::from_state(repo.state()) {
+            None | Some(Operation::Bisect) => {}
+            Some(operation) => anyhow::bail!("Can't commit in the middle of a {operation}"),
+        }
+
         let config = repo.config()?;
 
         let name = config.get_string("user.name")?;
@@ -173,8 +699,54 @@ impl RepoCache {
         Ok(())
     }
 
+    /// Lists what the next commit will contain, like `git diff --cached --numstat`.
+    /// Files that are only partially staged show only their staged lines.
+    pub fn staged_files(&self) -> Result<Vec<StagedFile>> {
+        let repo = self.repo.lock().unwrap();
+
+        // An unborn HEAD has no tree, so everything in the index is new
+        let head_tree = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(_) => None,
+        };
+        let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+
+        let mut staged = vec![];
+        for (i, delta) in diff.deltas().enumerate() {
+            let path = delta
+                .new_file()
+                .path()
+                .or(delta.old_file().path())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+
+            // Binary files have no patch and therefore no line stats
+            let (insertions, deletions) = match git2::Patch::from_diff(&diff, i)? {
+                Some(patch) => {
+                    let (_, insertions, deletions) = patch.line_stats()?;
+                    (insertions, deletions)
+                }
+                None => (0, 0),
+            };
+
+            staged.push(StagedFile {
+                path,
+                delta: delta.status(),
+                insertions,
+                deletions,
+            });
+        }
+
+        Ok(staged)
+    }
+
     /// Returns a git diff for a file.
     pub fn diff(&self, path: &Path) -> Result<String> {
+        self.diff_with_mode(path, DiffMode::HeadToWorkdir)
+    }
+
+    /// Returns a git diff for a file, comparing the states given by `mode`.
+    pub fn diff_with_mode(&self, path: &Path, mode: DiffMode) -> Result<String> {
         let repo = self.repo.lock().unwrap();
 
         // Get the HEAD tree to compare against
@@ -188,8 +760,15 @@ impl RepoCache {
         diff_opts.pathspec(path);
 
         // 4. Generate the diff
-        //    (Comparing HEAD tree to the working directory)
-        let diff = repo.diff_tree_to_workdir(Some(&head_tree), Some(&mut diff_opts))?;
+        let diff = match mode {
+            DiffMode::HeadToWorkdir => {
+                repo.diff_tree_to_workdir(Some(&head_tree), Some(&mut diff_opts))?
+            }
+            DiffMode::IndexToWorkdir => repo.diff_index_to_workdir(None, Some(&mut diff_opts))?,
+            DiffMode::HeadToIndex => {
+                repo.diff_tree_to_index(Some(&head_tree), None, Some(&mut diff_opts))?
+            }
+        };
 
         // 5. Print the diff in patch format
         let mut result = String::new();
@@ -216,43 +795,745 @@ impl RepoCache {
     /// so you can quickly access it again through Repository.statuses
     /// This function is threaded and does not return anything.
     pub fn refresh(&self) -> Result<()> {
-        let repo = self.repo.clone();
-        let r_statuses = self.statuses.clone();
-        let local_refresh = self.local_refresh.clone();
+        let cache = self.clone();
 
         std::thread::spawn(move || {
+            let _refreshing = cache.refresh_lock.lock().unwrap();
+
             let mut status_opts = StatusOptions::new();
             status_opts
                 .include_untracked(true) // Show untracked files
                 .recurse_untracked_dirs(true); // Show untracked files within dirs
 
             // Get the status of all files in the repo
-            let binding = repo.lock().unwrap();
-            let statuses = binding.statuses(Some(&mut status_opts)).unwrap();
-
-            // Iterate through each file's status
-            r_statuses.lock().unwrap().clear();
-            for entry in statuses.iter() {
-                let path = entry.path().unwrap_or("<none>");
-                // debug!("{path}");
-                r_statuses.lock().unwrap().push(FileStatus {
-                    path: PathBuf::from(path),
-                    status: entry.status(),
-                });
+            {
+                let binding = cache.repo.lock().unwrap();
+                let statuses = binding.statuses(Some(&mut status_opts)).unwrap();
+
+                // Iterate through each file's status
+                cache.statuses.lock().unwrap().clear();
+                for entry in statuses.iter() {
+                    let path = entry.path().unwrap_or("<none>");
+                    // debug!("{path}");
+                    cache.statuses.lock().unwrap().push(FileStatus {
+                        path: PathBuf::from(path),
+                        status: entry.status(),
+                    });
+                }
             }
             debug!("Repository status refreshed.");
-            *local_refresh.lock().unwrap() = Some(SystemTime::now());
 
+            cache.refresh_details();
+            *cache.local_refresh.lock().unwrap() = Some(SystemTime::now());
+        });
 
+        Ok(())
+    }
 
+    /// Reloads the log, staged files, stashes, operation and branches.
+    /// Each one is updated on its own, as some can fail where others don't,
+    /// like the log of an unborn HEAD.
+    fn refresh_details(&self) {
+        update(&self.log, "log", self.refresh_log(self.get_log_depth()));
+        update(&self.staged, "staged files", self.staged_files());
+        update(&self.stashes, "stashes", self.stash_list());
+        update(&self.operation, "operation", self.operation_state());
+        update(&self.branches, "branches", self.branches());
+    }
+}
+
+/// Watches a set of repositories, refreshing a summary of each on a background thread.
+pub struct RepoManager {
+    pub roots: Arc<Mutex<Vec<PathBuf>>>,
+    pub summaries: Arc<Mutex<Vec<RepoSummary>>>,
+    /// Wakes the background thread up for an early refresh
+    wake: mpsc::Sender<()>,
+}
 
+impl RepoManager {
+    /// Starts watching `roots`, refreshing them every `interval`.
+    /// The background thread ends once the manager is dropped.
+    pub fn new(roots: Vec<PathBuf>, interval: Duration) -> Self {
+        let (wake, woken) = mpsc::channel();
+        let manager = Self {
+            roots: Arc::new(Mutex::new(roots)),
+            summaries: Arc::new(Mutex::new(vec![])),
+            wake,
+        };
+
+        // All refreshes happen on this one thread, so a slow one can't overwrite a newer one
+        let roots = manager.roots.clone();
+        let summaries = manager.summaries.clone();
+        std::thread::spawn(move || loop {
+            refresh_summaries(&roots, &summaries);
+            match woken.recv_timeout(interval) {
+                // Several requests that came in at once only need one refresh
+                Ok(()) => while woken.try_recv().is_ok() {},
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    debug!("Repository manager stopped.");
+                    break;
+                }
+            }
         });
 
-        // todo: thread
+        manager
+    }
 
-        let log = self.refresh_log(10)?;
-        *self.log.lock().unwrap() = log;
+    pub fn get_roots(&self) -> Vec<PathBuf> {
+        (*self.roots.lock().unwrap()).clone()
+    }
 
-        Ok(())
+    pub fn get_summaries(&self) -> Vec<RepoSummary> {
+        (*self.summaries.lock().unwrap()).clone()
+    }
+
+    /// Replaces the watched repositories and refreshes them right away.
+    pub fn set_roots(&self, roots: Vec<PathBuf>) {
+        *self.roots.lock().unwrap() = roots;
+        self.refresh();
+    }
+
+    /// Refreshes all summaries now instead of waiting for the next interval.
+    /// This function is threaded and does not return anything.
+    pub fn refresh(&self) {
+        // The thread only stops once the manager is dropped, so it is still listening
+        _ = self.wake.send(());
+    }
+}
+
+/// Stores a freshly computed value in its cache, keeping the old one if that failed
+fn update<T>(cache: &Mutex<T>, name: &str, value: Result<T>) {
+    match value {
+        Ok(value) => *cache.lock().unwrap() = value,
+        Err(e) => warn!("Failed to refresh {name}: {e}"),
+    }
+}
+
+fn refresh_summaries(roots: &Mutex<Vec<PathBuf>>, summaries: &Mutex<Vec<RepoSummary>>) {
+    let roots = roots.lock().unwrap().clone();
+    let fresh = roots
+        .iter()
+        .map(|root| {
+            let mut summary = RepoCache::open(root)
+                .and_then(|repo| repo.summary())
+                .unwrap_or_else(|e| RepoSummary {
+                    root: root.clone(),
+                    branch: None,
+                    dirty_files: 0,
+                    ahead: 0,
+                    behind: 0,
+                    error: Some(e.to_string()),
+                });
+            // Keep the root as it was given, so callers can find it again
+            summary.root = root.clone();
+            summary
+        })
+        .collect();
+    *summaries.lock().unwrap() = fresh;
+    debug!("Refreshed {} repository summaries.", roots.len());
+}
+
+/// Writes a stash that only contains `paths` and removes their changes from index and working directory.
+///
+/// Like git, this creates an index commit, an optional commit for untracked files and a
+/// working directory commit on top of HEAD, and records the latter in `refs/stash`.
+fn stash_paths(repo: &Repository, paths: &[PathBuf]) -> Result<Oid> {
+    let workdir = repo.workdir().context("Can't stash in a bare repository")?;
+    let sig = repo.signature()?;
+    let head_ref = repo.head()?;
+    let head = head_ref.peel_to_commit()?;
+    let head_tree = head.tree()?;
+    let mut index = repo.index()?;
+    // Without this, git doesn't trust the executable bit on disk and keeps the mode from the index
+    let filemode = repo.config()?.get_bool("core.filemode").unwrap_or(true);
+
+    // Paths are taken literally throughout, so `p/[id].ts` doesn't also match `p/i.ts`
+    let mut status_opts = StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .disable_pathspec_match(true);
+    for path in paths {
+        status_opts.pathspec(path);
+    }
+
+    let mut index_update = TreeUpdateBuilder::new();
+    let mut workdir_update = TreeUpdateBuilder::new();
+    let mut untracked_update = TreeUpdateBuilder::new();
+    let mut any_untracked = false;
+
+    let statuses = repo.statuses(Some(&mut status_opts))?;
+    if statuses.is_empty() {
+        anyhow::bail!("No local changes to save");
+    }
+    for entry in statuses.iter() {
+        let Some(path) = entry.path() else { continue };
+        let full_path = workdir.join(path);
+
+        if entry.status().is_wt_new() && !entry.status().is_index_new() {
+            let mode = workdir_mode(&full_path, filemode, FileMode::Blob)?;
+            untracked_update.upsert(path, repo.blob_path(&full_path)?, mode);
+            any_untracked = true;
+            continue;
+        }
+
+        let index_entry = index.get_path(Path::new(path), 0);
+        let mode = match &index_entry {
+            Some(e) if e.mode == u32::from(FileMode::BlobExecutable) => FileMode::BlobExecutable,
+            _ => FileMode::Blob,
+        };
+        match &index_entry {
+            Some(e) => index_update.upsert(path, e.id, mode),
+            None => index_update.remove(path),
+        };
+        if full_path.exists() {
+            let workdir_mode = workdir_mode(&full_path, filemode, mode)?;
+            workdir_update.upsert(path, repo.blob_path(&full_path)?, workdir_mode);
+        } else {
+            workdir_update.remove(path);
+        }
+    }
+
+    let branch = head_ref.shorthand().unwrap_or("(no branch)");
+    let short_id = head.as_object().short_id()?;
+    let subject = format!(
+        "{branch}: {} {}",
+        short_id.as_str().unwrap_or_default(),
+        head.summary().unwrap_or_default()
+    );
+
+    let index_tree = repo.find_tree(index_update.create_updated(repo, &head_tree)?)?;
+    let index_commit = repo.find_commit(repo.commit(
+        None,
+        &sig,
+        &sig,
+        &format!("index on {subject}"),
+        &index_tree,
+        &[&head],
+    )?)?;
+
+    let mut parents = vec![head.clone(), index_commit];
+    if any_untracked {
+        let empty_tree = repo.find_tree(repo.treebuilder(None)?.write()?)?;
+        let untracked_tree = repo.find_tree(untracked_update.create_updated(repo, &empty_tree)?)?;
+        parents.push(repo.find_commit(repo.commit(
+            None,
+            &sig,
+            &sig,
+            &format!("untracked files on {subject}"),
+            &untracked_tree,
+            &[],
+        )?)?);
+    }
+
+    let message = format!("WIP on {subject}");
+    let workdir_tree = repo.find_tree(workdir_update.create_updated(repo, &head_tree)?)?;
+    let stash_id = repo.commit(
+        None,
+        &sig,
+        &sig,
+        &message,
+        &workdir_tree,
+        &parents.iter().collect::<Vec<_>>(),
+    )?;
+
+    // The stash list is read from the reflog, so make sure there is one
+    repo.reference_ensure_log("refs/stash")?;
+    repo.reference("refs/stash", stash_id, true, &message)?;
+
+    // Put the stashed paths back to HEAD, leaving everything else alone
+    for path in paths {
+        index.remove_path(path)?;
+        if let Ok(entry) = head_tree.get_path(path) {
+            index.add(&IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: entry.filemode() as u32,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: entry.id(),
+                flags: 0,
+                flags_extended: 0,
+                path: path.to_string_lossy().as_bytes().to_vec(),
+            })?;
+        }
+    }
+    index.write()?;
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout
+        .force()
+        .remove_untracked(true)
+        .disable_pathspec_match(true);
+    for path in paths {
+        checkout.path(path.as_path());
+    }
+    repo.checkout_head(Some(&mut checkout))?;
+
+    Ok(stash_id)
+}
+
+/// The mode git records for a file in the working directory. Its executable bit is only
+/// trusted with `filemode` (`core.filemode`), otherwise the file keeps `fallback`.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn workdir_mode(path: &Path, filemode: bool, fallback: FileMode) -> Result<FileMode> {
+    #[cfg(unix)]
+    if filemode {
+        use std::os::unix::fs::PermissionsExt;
+        let executable = std::fs::metadata(path)?.permissions().mode() & 0o100 != 0;
+        return Ok(if executable {
+            FileMode::BlobExecutable
+        } else {
+            FileMode::Blob
+        });
+    }
+    Ok(fallback)
+}
+
+/// Puts the files an operation touched back to their HEAD version, in the index and the
+/// working tree, like `git reset --merge`. Uncommitted changes to other files are kept.
+fn reset_operation_paths(repo: &Repository) -> Result<()> {
+    let head = repo.head()?.peel_to_commit()?;
+    let mut index = repo.index()?;
+    // The cached index may predate the operation if git itself started it
+    index.read(false)?;
+
+    // The operation left its result in the index, so that is what differs from HEAD
+    let mut paths = vec![];
+    let diff = repo.diff_tree_to_index(Some(&head.tree()?), Some(&index), None)?;
+    for delta in diff.deltas() {
+        paths.extend(delta.old_file().path().map(Path::to_path_buf));
+        paths.extend(delta.new_file().path().map(Path::to_path_buf));
+    }
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        for entry in [conflict.ancestor, conflict.our, conflict.their]
+            .into_iter()
+            .flatten()
+        {
+            let path = String::from_utf8_lossy(&entry.path).to_string();
+            paths.push(PathBuf::from(path));
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    repo.reset_default(Some(head.as_object()), &paths)?;
+
+    // Files the operation added are not in HEAD, so they have to be removed as untracked
+    let mut checkout = CheckoutBuilder::new();
+    checkout
+        .force()
+        .remove_untracked(true)
+        .disable_pathspec_match(true);
+    for path in &paths {
+        checkout.path(path.as_path());
+    }
+    repo.checkout_head(Some(&mut checkout))?;
+
+    Ok(())
+}
+
+/// Refuses to conclude a multi-commit cherry-pick or revert: the remaining commits
+/// live in `.git/sequencer`, which only git knows how to drive.
+fn check_sequence(state: &OperationState) -> Result<()> {
+    if state.sequence {
+        anyhow::bail!(
+            "This {} spans several commits, continue or abort it with git",
+            state.operation
+        );
+    }
+    Ok(())
+}
+
+/// Creates the commit that concludes a merge, cherry-pick or revert, using the prepared message.
+fn commit_operation(repo: &Repository, state: &OperationState) -> Result<Oid> {
+    let committer = repo.signature()?;
+    let head = repo.head()?.peel_to_commit()?;
+    let heads = state
+        .heads
+        .iter()
+        .map(|id| repo.find_commit(Oid::from_str(id)?))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A cherry-pick keeps the author of the picked commit
+    let author = match (state.operation, heads.first()) {
+        (Operation::CherryPick, Some(picked)) => picked.author().to_owned(),
+        _ => committer.clone(),
+    };
+
+    let mut parents = vec![head];
+    if state.operation == Operation::Merge {
+        parents.extend(heads);
+    }
+
+    let message = git2::message_prettify(state.message.as_deref().unwrap_or_default(), Some(b'#'))?;
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let commit_id = repo.commit(
+        Some("HEAD"),
+        &author,
+        &committer,
+        &message,
+        &tree,
+        &parents.iter().collect::<Vec<_>>(),
+    )?;
+    debug!("Concluded {} with {}", state.operation, commit_id);
+    Ok(commit_id)
+}
+
+fn open_rebase(repo: &Repository) -> Result<Rebase<'_>> {
+    repo.open_rebase(None)
+        .context("This rebase was started by another tool, continue it there")
+}
+
+/// Commits the current step of a rebase, unless it is skipped, and applies the remaining
+/// steps until the next conflict or the end.
+fn continue_rebase(repo: &Repository, rebase: &mut Rebase, commit_current: bool) -> Result<()> {
+    let sig = repo.signature()?;
+
+    let commit_step = |rebase: &mut Rebase| match rebase.commit(None, &sig, None) {
+        // The step has no changes left, e.g. because they are already upstream
+        Err(e) if e.code() == ErrorCode::Applied => Ok(()),
+        result => result.map(|_| ()),
+    };
+
+    if commit_current {
+        commit_step(rebase)?;
+    }
+    while let Some(step) = rebase.next() {
+        step?;
+        if repo.index()?.has_conflicts() {
+            debug!("Rebase stopped at a conflict");
+            return Ok(());
+        }
+        commit_step(rebase)?;
+    }
+    rebase.finish(Some(&sig))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fresh repository with one commit that contains `files`
+    fn test_repo(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("nanogit-{name}-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&root);
+        let repo = Repository::init(&root).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+
+        let mut index = repo.index().unwrap();
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "committed\n").unwrap();
+            index.add_path(Path::new(file)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = repo.signature().unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])
+            .unwrap();
+        root
+    }
+
+    /// Commits `files` with their contents on top of `parent`, moving `update_ref` to the commit
+    fn commit_files(
+        repo: &Repository,
+        parent: Oid,
+        files: &[(&str, &str)],
+        update_ref: Option<&str>,
+    ) -> Oid {
+        let parent = repo.find_commit(parent).unwrap();
+        let mut update = TreeUpdateBuilder::new();
+        for (file, content) in files {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            update.upsert(*file, blob, FileMode::Blob);
+        }
+        let tree = update
+            .create_updated(repo, &parent.tree().unwrap())
+            .unwrap();
+        let tree = repo.find_tree(tree).unwrap();
+        let sig = repo.signature().unwrap();
+        repo.commit(update_ref, &sig, &sig, "change", &tree, &[&parent])
+            .unwrap()
+    }
+
+    /// Sets up a merge of a side branch that conflicts with HEAD on `a.txt` and adds `new.txt`.
+    /// Returns the commits on both sides.
+    fn conflicted_merge(root: &Path) -> (Oid, Oid) {
+        let repo = Repository::open(root).unwrap();
+        let base = repo.head().unwrap().target().unwrap();
+        let theirs = commit_files(
+            &repo,
+            base,
+            &[("a.txt", "theirs\n"), ("new.txt", "theirs\n")],
+            None,
+        );
+        let ours = commit_files(&repo, base, &[("a.txt", "ours\n")], Some("HEAD"));
+        // Leave other files alone, they may have changes the merge has to keep
+        repo.checkout_head(Some(CheckoutBuilder::new().force().path("a.txt")))
+            .unwrap();
+
+        let theirs_commit = repo.find_annotated_commit(theirs).unwrap();
+        repo.merge(&[&theirs_commit], None, None).unwrap();
+        assert!(repo.index().unwrap().has_conflicts());
+        (ours, theirs)
+    }
+
+    #[test]
+    fn stash_paths_keeps_other_changes() {
+        let root = test_repo("stash-paths", &["a.txt", "p/[id].ts", "p/i.ts"]);
+        for file in ["a.txt", "p/[id].ts", "p/i.ts", "new.txt"] {
+            std::fs::write(root.join(file), "changed\n").unwrap();
+        }
+        let read = |file: &str| std::fs::read_to_string(root.join(file)).unwrap();
+
+        let repo = RepoCache::open(&root).unwrap();
+        repo.stash_paths(&[PathBuf::from("p/[id].ts"), PathBuf::from("new.txt")])
+            .unwrap();
+
+        assert_eq!(read("p/[id].ts"), "committed\n");
+        assert!(!root.join("new.txt").exists());
+        // `p/[id].ts` must not be treated as a glob that matches `p/i.ts`
+        assert_eq!(read("p/i.ts"), "changed\n");
+        assert_eq!(read("a.txt"), "changed\n");
+        assert_eq!(repo.stash_list().unwrap().len(), 1);
+
+        repo.stash_pop(0).unwrap();
+
+        assert_eq!(read("p/[id].ts"), "changed\n");
+        assert_eq!(read("new.txt"), "changed\n");
+        assert_eq!(read("p/i.ts"), "changed\n");
+        assert!(repo.stash_list().unwrap().is_empty());
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stash_paths_keeps_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = test_repo("stash-mode", &["run.sh"]);
+        let set_mode = |file: &str, mode: u32| {
+            let permissions = std::fs::Permissions::from_mode(mode);
+            std::fs::set_permissions(root.join(file), permissions).unwrap();
+        };
+        let is_executable = |file: &str| {
+            let metadata = std::fs::metadata(root.join(file)).unwrap();
+            metadata.permissions().mode() & 0o100 != 0
+        };
+        std::fs::write(root.join("new.sh"), "new\n").unwrap();
+        set_mode("new.sh", 0o755);
+        set_mode("run.sh", 0o755);
+
+        let repo = RepoCache::open(&root).unwrap();
+        let paths = [PathBuf::from("new.sh"), PathBuf::from("run.sh")];
+        repo.stash_paths(&paths).unwrap();
+
+        let git = Repository::open(&root).unwrap();
+        let stash = git.find_commit(git.refname_to_id("refs/stash").unwrap());
+        let stash = stash.unwrap();
+        let tree_mode = |commit: &git2::Commit, file: &str| {
+            commit.tree().unwrap().get_name(file).unwrap().filemode()
+        };
+        let executable = i32::from(FileMode::BlobExecutable);
+        assert_eq!(tree_mode(&stash, "run.sh"), executable);
+        let untracked = stash.parent(2).unwrap();
+        assert_eq!(tree_mode(&untracked, "new.sh"), executable);
+
+        repo.stash_pop(0).unwrap();
+
+        assert!(is_executable("new.sh"));
+        assert!(is_executable("run.sh"));
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn stash_paths_without_changes() {
+        let root = test_repo("stash-clean", &["a.txt", "b.txt"]);
+        std::fs::write(root.join("b.txt"), "changed\n").unwrap();
+
+        let repo = RepoCache::open(&root).unwrap();
+        assert!(repo.stash_paths(&[PathBuf::from("a.txt")]).is_err());
+        assert!(repo.stash_list().unwrap().is_empty());
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn restore_index_undoes_staging() {
+        let root = test_repo("restore-index", &["a.txt", "b.txt"]);
+        std::fs::write(root.join("a.txt"), "changed\n").unwrap();
+        let repo = RepoCache::open(&root).unwrap();
+        let paths = |repo: &RepoCache| {
+            let staged = repo.staged_files().unwrap();
+            staged.into_iter().map(|file| file.path).collect::<Vec<_>>()
+        };
+
+        let snapshot = repo.snapshot_index().unwrap();
+        repo.stage(Path::new("a.txt")).unwrap();
+        assert_eq!(paths(&repo), vec![PathBuf::from("a.txt")]);
+        repo.restore_index(&snapshot).unwrap();
+        assert!(paths(&repo).is_empty());
+
+        let snapshot = repo.snapshot_index().unwrap();
+        repo.unstage(Path::new("b.txt")).unwrap();
+        assert_eq!(paths(&repo), vec![PathBuf::from("b.txt")]);
+        repo.restore_index(&snapshot).unwrap();
+        assert!(paths(&repo).is_empty());
+
+        // Only the index is restored, the working directory keeps its changes
+        let content = std::fs::read_to_string(root.join("a.txt")).unwrap();
+        assert_eq!(content, "changed\n");
+        assert_eq!(repo.snapshot_index().unwrap().entries.len(), 2);
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn refresh_unborn_head() {
+        let root = std::env::temp_dir().join(format!("nanogit-unborn-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&root);
+        let git = Repository::init(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "new\n").unwrap();
+        let mut index = git.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        let repo = RepoCache::open(&root).unwrap();
+        repo.refresh().unwrap();
+        while !repo.is_local_refreshed() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // There is no log yet, but what is staged must still show up
+        assert!(repo.get_log().is_empty());
+        assert_eq!(repo.get_staged().len(), 1);
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn branches_with_broken_upstream() {
+        let root = test_repo("broken-upstream", &["a.txt"]);
+        let git = Repository::open(&root).unwrap();
+        let head = git.head().unwrap().shorthand().unwrap().to_string();
+        git.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        let mut config = git.config().unwrap();
+        config
+            .set_str(&format!("branch.{head}.remote"), "origin")
+            .unwrap();
+        let merge = format!("refs/heads/{head}");
+        config
+            .set_str(&format!("branch.{head}.merge"), &merge)
+            .unwrap();
+        // The upstream points to a commit that isn't there, so it can't be compared
+        let upstream = git.path().join("refs/remotes/origin").join(&head);
+        std::fs::create_dir_all(upstream.parent().unwrap()).unwrap();
+        std::fs::write(upstream, format!("{}\n", "1".repeat(40))).unwrap();
+
+        let repo = RepoCache::open(&root).unwrap();
+        let branches = repo.branches().unwrap();
+
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].name, head);
+        assert_eq!(branches[0].upstream, Some(format!("origin/{head}")));
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn abort_merge_keeps_unrelated_changes() {
+        let root = test_repo("abort-merge", &["a.txt", "b.txt"]);
+        let read = |file: &str| std::fs::read_to_string(root.join(file)).unwrap();
+        std::fs::write(root.join("b.txt"), "dirty\n").unwrap();
+        conflicted_merge(&root);
+
+        let repo = RepoCache::open(&root).unwrap();
+        repo.abort_operation().unwrap();
+
+        assert_eq!(read("a.txt"), "ours\n");
+        assert!(!root.join("new.txt").exists());
+        // The merge didn't touch this file, so the change from before it stays
+        assert_eq!(read("b.txt"), "dirty\n");
+        assert!(repo.operation_state().unwrap().is_none());
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn skip_cherry_pick() {
+        let root = test_repo("skip-cherry-pick", &["a.txt"]);
+        let read = |file: &str| std::fs::read_to_string(root.join(file)).unwrap();
+        let git = Repository::open(&root).unwrap();
+        let base = git.head().unwrap().target().unwrap();
+        let picked = commit_files(
+            &git,
+            base,
+            &[("a.txt", "picked\n"), ("new.txt", "picked\n")],
+            None,
+        );
+        let ours = commit_files(&git, base, &[("a.txt", "ours\n")], Some("HEAD"));
+        git.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        git.cherrypick(&git.find_commit(picked).unwrap(), None)
+            .unwrap();
+
+        let repo = RepoCache::open(&root).unwrap();
+        let state = repo.operation_state().unwrap().unwrap();
+        assert_eq!(state.operation, Operation::CherryPick);
+        repo.skip_operation().unwrap();
+
+        assert_eq!(read("a.txt"), "ours\n");
+        assert!(!root.join("new.txt").exists());
+        assert_eq!(git.head().unwrap().target(), Some(ours));
+        assert!(repo.operation_state().unwrap().is_none());
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn continue_merge_commits_both_parents() {
+        let root = test_repo("continue-merge", &["a.txt"]);
+        let (ours, theirs) = conflicted_merge(&root);
+
+        // Resolve the conflict and prepare the message the way git does
+        let git = Repository::open(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "resolved\n").unwrap();
+        let mut index = git.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::write(
+            git.path().join("MERGE_MSG"),
+            "Merge branch 'side'\n\n# Conflicts:\n#\ta.txt\n",
+        )
+        .unwrap();
+
+        let repo = RepoCache::open(&root).unwrap();
+        repo.continue_operation().unwrap();
+
+        let merge = git.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(merge.parent_ids().collect::<Vec<_>>(), vec![ours, theirs]);
+        assert_eq!(merge.message(), Some("Merge branch 'side'\n"));
+        let tree = merge.tree().unwrap();
+        let blob = tree.get_name("a.txt").unwrap().to_object(&git).unwrap();
+        assert_eq!(blob.as_blob().unwrap().content(), b"resolved\n");
+        assert!(tree.get_name("new.txt").is_some());
+        assert!(repo.operation_state().unwrap().is_none());
+
+        _ = std::fs::remove_dir_all(&root);
     }
 }
@@ -5,9 +5,16 @@ use eframe::egui::{self, Id, Response, Sense, Stroke, Ui, WidgetText};
 use egui_notify::Toasts;
 use egui_phosphor::regular::*;
 use log::{debug, info};
-use nanogit::{RepoCache, Status};
+use nanogit::{
+    Delta, DiffMode, IndexSnapshot, Operation, OperationState, RepoCache, RepoManager, Status,
+    TreeItem,
+};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    time::Duration,
+};
 
 fn main() -> eframe::Result {
     std::env::set_var("RUST_LOG", "debug");
@@ -24,16 +31,29 @@ fn main() -> eframe::Result {
 }
 
 #[derive(Serialize, Deserialize)]
-
+#[serde(default)]
 struct GitApp {
     #[serde(skip)]
     repo: Option<RepoCache>,
     // The root of the repo, for reopening on the next run
     repo_root: Option<PathBuf>,
-    commit_message: String,
+    // UI state of every repo we have seen, keyed by repo root
+    repo_states: BTreeMap<PathBuf, RepoState>,
     #[serde(skip)]
     toasts: Toasts,
-    selected_file: Option<usize>,
+    // The index before the last stage/unstage, for a single level of undo
+    #[serde(skip)]
+    staging_undo: Option<IndexSnapshot>,
+    #[serde(skip)]
+    time_travel: Option<TimeTravel>,
+    // An abort or skip of the operation in progress, waiting to be confirmed
+    #[serde(skip)]
+    confirm_operation: Option<OperationAction>,
+    // Repositories watched on the dashboard
+    monitored_repos: Vec<PathBuf>,
+    show_dashboard: bool,
+    #[serde(skip)]
+    repo_manager: Option<RepoManager>,
 }
 
 impl Default for GitApp {
@@ -41,13 +61,63 @@ impl Default for GitApp {
         Self {
             repo: None,
             repo_root: None,
-            commit_message: Default::default(),
+            repo_states: Default::default(),
             toasts: Toasts::default(),
+            staging_undo: None,
+            time_travel: None,
+            confirm_operation: None,
+            monitored_repos: vec![],
+            show_dashboard: false,
+            repo_manager: None,
+        }
+    }
+}
+
+/// UI state that belongs to a single repository
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct RepoState {
+    commit_message: String,
+    selected_file: Option<PathBuf>,
+    // Files picked with ctrl/cmd-click, e.g. for stashing. Not kept across restarts,
+    // as the files may have changed in the meantime.
+    #[serde(skip)]
+    selected_files: BTreeSet<PathBuf>,
+    // Titles of the sections that are expanded
+    open_sections: BTreeSet<String>,
+    diff_mode: DiffMode,
+    log_depth: usize,
+}
+
+impl Default for RepoState {
+    fn default() -> Self {
+        Self {
+            commit_message: Default::default(),
             selected_file: None,
+            selected_files: Default::default(),
+            open_sections: BTreeSet::from(["Changes".to_string(), "Commit preview".to_string()]),
+            diff_mode: DiffMode::default(),
+            log_depth: 10,
         }
     }
 }
 
+/// An action on the operation in progress that throws away changes
+#[derive(Clone, Copy)]
+enum OperationAction {
+    Abort,
+    Skip,
+}
+
+/// A past revision that is being browsed, read-only
+struct TimeTravel {
+    commit: String,
+    summary: String,
+    files: Vec<TreeItem>,
+    selected_file: Option<PathBuf>,
+    content: String,
+}
+
 impl GitApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
         let mut fd = egui::FontDefinitions::default();
@@ -56,18 +126,176 @@ impl GitApp {
 
         cc.egui_ctx.set_fonts(fd);
 
+        let mut state = Self::default();
         if let Some(storage) = cc.storage {
-            let mut state =
-                eframe::get_value::<GitApp>(storage, eframe::APP_KEY).unwrap_or_default();
+            state = eframe::get_value::<GitApp>(storage, eframe::APP_KEY).unwrap_or_default();
             info!("storage present {:?}", state.repo_root);
 
-            if let Some(root) = state.repo_root.as_ref() {
-                state.repo = RepoCache::open(&root).ok();
-                _ = state.repo.as_ref().map(|r| r.refresh());
-                return state;
+            if let Some(root) = state.repo_root.clone() {
+                match RepoCache::open(&root) {
+                    Ok(repo) => state.set_repo(repo, &cc.egui_ctx),
+                    Err(e) => {
+                        state.toasts.error(e.to_string());
+                    }
+                }
+            }
+        }
+
+        // Watched repositories are refreshed in the background, whether the dashboard is open or not
+        state.repo_manager = Some(RepoManager::new(
+            state.monitored_repos.clone(),
+            Duration::from_secs(10),
+        ));
+        state
+    }
+
+    /// Make `repo` the current repository, restoring the UI state we kept for it.
+    fn set_repo(&mut self, repo: RepoCache, ctx: &egui::Context) {
+        let root = repo.get_root();
+        let state = self.repo_states.entry(root.clone()).or_default();
+        repo.set_log_depth(state.log_depth);
+        if let Err(e) = repo.refresh() {
+            self.toasts.error(e.to_string());
+        }
+        load_diff(ctx, &repo, state.selected_file.as_ref(), state.diff_mode);
+        self.staging_undo = None;
+        self.time_travel = None;
+        self.confirm_operation = None;
+        self.repo = Some(repo);
+        self.repo_root = Some(root);
+    }
+
+    /// An overview of all watched repositories. Clicking one opens it.
+    fn dashboard(&mut self, ctx: &egui::Context) {
+        let Some(manager) = &self.repo_manager else {
+            return;
+        };
+        // Pick up what the manager refreshed in the background
+        ctx.request_repaint_after(Duration::from_secs(1));
+        let summaries = manager.get_summaries();
+
+        let mut open = None;
+        let mut add = None;
+        let mut remove = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(format!("{PLUS} Watch repository")).clicked() {
+                    match pick_repo() {
+                        Ok(folder) => add = Some(folder),
+                        Err(e) => {
+                            self.toasts.error(e.to_string());
+                        }
+                    }
+                }
+                if ui.button("Refresh").clicked() {
+                    manager.refresh();
+                }
+            });
+            ui.separator();
+
+            if self.monitored_repos.is_empty() {
+                ui.label("No repositories are watched yet.");
+                return;
+            }
+
+            egui::Grid::new("dashboard")
+                .striped(true)
+                .num_columns(5)
+                .show(ui, |ui| {
+                    ui.strong("Repository");
+                    ui.strong("Branch");
+                    ui.strong("Changes");
+                    ui.strong("Ahead / behind");
+                    ui.end_row();
+
+                    for root in &self.monitored_repos {
+                        let name = root
+                            .file_name()
+                            .map(|f| f.to_string_lossy().to_string())
+                            .unwrap_or_else(|| root.display().to_string());
+                        if ui
+                            .link(name)
+                            .on_hover_text(root.display().to_string())
+                            .clicked()
+                        {
+                            open = Some(root.clone());
+                        }
+
+                        match summaries.iter().find(|summary| &summary.root == root) {
+                            None => {
+                                ui.spinner();
+                                ui.label("");
+                                ui.label("");
+                            }
+                            Some(summary) => match &summary.error {
+                                Some(error) => {
+                                    ui.colored_label(
+                                        ui.visuals().error_fg_color,
+                                        format!("{WARNING} Can't read"),
+                                    )
+                                    .on_hover_text(error);
+                                    ui.label("");
+                                    ui.label("");
+                                }
+                                None => {
+                                    ui.label(format!(
+                                        "{GIT_BRANCH} {}",
+                                        summary.branch.as_deref().unwrap_or("detached HEAD")
+                                    ));
+                                    if summary.dirty_files == 0 {
+                                        ui.label("clean");
+                                    } else {
+                                        ui.colored_label(
+                                            ui.visuals().warn_fg_color,
+                                            format!("{} changed", summary.dirty_files),
+                                        );
+                                    }
+                                    let ahead_behind = format!(
+                                        "{ARROW_UP}{} {ARROW_DOWN}{}",
+                                        summary.ahead, summary.behind
+                                    );
+                                    if summary.ahead == 0 && summary.behind == 0 {
+                                        ui.label(ahead_behind);
+                                    } else {
+                                        ui.colored_label(ui.visuals().warn_fg_color, ahead_behind);
+                                    }
+                                }
+                            },
+                        }
+
+                        if ui.button(X).on_hover_text("Stop watching").clicked() {
+                            remove = Some(root.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+        let roots_changed = add.is_some() || remove.is_some();
+        if let Some(folder) = add {
+            if !self.monitored_repos.contains(&folder) {
+                self.monitored_repos.push(folder);
+            }
+        }
+        if let Some(root) = &remove {
+            self.monitored_repos.retain(|r| r != root);
+        }
+        if roots_changed {
+            manager.set_roots(self.monitored_repos.clone());
+        }
+
+        if let Some(root) = open {
+            match RepoCache::open(&root) {
+                Ok(repo) => {
+                    self.set_repo(repo, ctx);
+                    self.show_dashboard = false;
+                }
+                Err(e) => {
+                    self.toasts.error(e.to_string());
+                }
             }
         }
-        Self::default()
     }
 }
 
@@ -87,14 +315,7 @@ impl eframe::App for GitApp {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open repository").clicked() {
                         match open_repo() {
-                            Ok(r) => {
-                                self.repo = Some(r);
-                                let repo = self.repo.as_ref().expect("This repo must exist");
-                                if let Err(e) = repo.refresh() {
-                                    self.toasts.error(e.to_string());
-                                }
-                                self.repo_root = Some(repo.get_root());
-                            }
+                            Ok(r) => self.set_repo(r, ui.ctx()),
                             Err(e) => {
                                 self.toasts.error(format!("{e}"));
                             }
@@ -102,6 +323,30 @@ impl eframe::App for GitApp {
                         ui.close_menu();
                     }
 
+                    ui.menu_button("Recent", |ui| {
+                        let mut reopen = None;
+                        for root in self.repo_states.keys() {
+                            if ui
+                                .add_enabled(
+                                    Some(root) != self.repo_root.as_ref(),
+                                    egui::Button::new(root.display().to_string()),
+                                )
+                                .clicked()
+                            {
+                                reopen = Some(root.clone());
+                            }
+                        }
+                        if let Some(root) = reopen {
+                            match RepoCache::open(&root) {
+                                Ok(r) => self.set_repo(r, ui.ctx()),
+                                Err(e) => {
+                                    self.toasts.error(format!("{e}"));
+                                }
+                            }
+                            ui.close_menu();
+                        }
+                    });
+
                     if let Some(repo) = self.repo.as_mut() {
                         if ui.button("Refresh").clicked() {
                             if let Err(e) = repo.refresh() {
@@ -110,6 +355,31 @@ impl eframe::App for GitApp {
                         }
                     }
                 });
+
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut self.show_dashboard, "Dashboard");
+                });
+
+                ui.menu_button("Edit", |ui| {
+                    if let Some(repo) = &self.repo {
+                        if ui
+                            .add_enabled(
+                                self.staging_undo.is_some(),
+                                egui::Button::new(format!(
+                                    "{ARROW_COUNTER_CLOCKWISE} Undo staging change"
+                                )),
+                            )
+                            .clicked()
+                        {
+                            if let Some(snapshot) = self.staging_undo.take() {
+                                if let Err(e) = repo.restore_index(&snapshot) {
+                                    self.toasts.error(e.to_string());
+                                }
+                            }
+                            ui.close_menu();
+                        }
+                    }
+                });
             });
             if let Some(repo) = &self.repo {
                 if !repo.is_local_refreshed() {
@@ -118,6 +388,163 @@ impl eframe::App for GitApp {
             }
         });
 
+        if let Some((repo, operation)) = self
+            .repo
+            .as_ref()
+            .and_then(|repo| Some((repo, repo.get_operation()?)))
+        {
+            egui::TopBottomPanel::top("operation").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        egui::RichText::new(format!("{WARNING} {}", operation_text(&operation)))
+                            .strong()
+                            .color(ui.visuals().warn_fg_color),
+                    );
+
+                    let supported = matches!(
+                        operation.operation,
+                        Operation::Merge
+                            | Operation::Rebase
+                            | Operation::CherryPick
+                            | Operation::Revert
+                    );
+                    if !supported {
+                        return;
+                    }
+                    if operation.sequence {
+                        ui.label("Part of a multi-commit sequence, continue or abort it with git");
+                        return;
+                    }
+
+                    let conflicted = repo.get_statuses().iter().any(|s| s.status.is_conflicted());
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        let mut result = None;
+                        if let Some(action) = self.confirm_operation {
+                            if ui.button("Cancel").clicked() {
+                                self.confirm_operation = None;
+                            }
+                            let (label, question) = match action {
+                                OperationAction::Abort => (
+                                    "Abort",
+                                    format!(
+                                        "Abort the {}? Its changes will be lost.",
+                                        operation.operation
+                                    ),
+                                ),
+                                OperationAction::Skip => (
+                                    "Skip",
+                                    "Skip this step? Its changes will be lost.".to_string(),
+                                ),
+                            };
+                            if ui.button(label).clicked() {
+                                self.confirm_operation = None;
+                                result = Some(match action {
+                                    OperationAction::Abort => repo.abort_operation(),
+                                    OperationAction::Skip => repo.skip_operation(),
+                                });
+                            }
+                            ui.label(question);
+                        } else {
+                            if ui.button("Abort").clicked() {
+                                self.confirm_operation = Some(OperationAction::Abort);
+                            }
+                            if operation.external {
+                                ui.label(
+                                    "Started outside of nanogit, continue or skip it with git",
+                                );
+                            } else {
+                                if operation.operation != Operation::Merge
+                                    && ui.button("Skip").clicked()
+                                {
+                                    self.confirm_operation = Some(OperationAction::Skip);
+                                }
+                                if ui
+                                    .add_enabled(!conflicted, egui::Button::new("Continue"))
+                                    .on_disabled_hover_text("Resolve and stage all conflicts first")
+                                    .clicked()
+                                {
+                                    result = Some(repo.continue_operation());
+                                }
+                            }
+                        }
+
+                        match result {
+                            Some(Ok(_)) => self.staging_undo = None,
+                            Some(Err(e)) => {
+                                self.toasts.error(e.to_string());
+                            }
+                            None => {}
+                        }
+                    });
+                });
+            });
+        } else {
+            // The operation was concluded elsewhere, so there is nothing left to confirm
+            self.confirm_operation = None;
+        }
+
+        if let (Some(repo), Some(time_travel)) = (&self.repo, &mut self.time_travel) {
+            let mut exit = false;
+            egui::TopBottomPanel::top("time travel").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "{CLOCK_COUNTER_CLOCKWISE} Viewing {} \"{}\" - historical state, read-only",
+                            &time_travel.commit[..7.min(time_travel.commit.len())],
+                            time_travel.summary
+                        ))
+                        .strong()
+                        .color(ui.visuals().warn_fg_color),
+                    );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button(format!("{X} Exit")).clicked() {
+                            exit = true;
+                        }
+                    });
+                });
+            });
+
+            egui::SidePanel::left("time travel files").show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    if let Some(path) =
+                        tree_view(ui, &time_travel.files, time_travel.selected_file.as_ref())
+                    {
+                        match repo.show_file(&time_travel.commit, &path) {
+                            Ok(content) => time_travel.content = content,
+                            Err(e) => time_travel.content = e.to_string(),
+                        }
+                        time_travel.selected_file = Some(path);
+                    }
+                });
+            });
+
+            egui::CentralPanel::default().show(ctx, |ui| match &time_travel.selected_file {
+                Some(path) => {
+                    ui.strong(path.display().to_string());
+                    egui::ScrollArea::both().show(ui, |ui| {
+                        egui::TextEdit::multiline(&mut time_travel.content.as_str())
+                            .code_editor()
+                            .desired_width(f32::INFINITY)
+                            .show(ui);
+                    });
+                }
+                None => {
+                    ui.label("Pick a file to see its content at this commit.");
+                }
+            });
+
+            if exit {
+                self.time_travel = None;
+            }
+            return;
+        }
+
+        if self.show_dashboard {
+            self.dashboard(ctx);
+            return;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // if let Some(repo) = self.repo.as_mut() {
             //     if ui.button("Status").clicked() {
@@ -127,120 +554,227 @@ impl eframe::App for GitApp {
             //     }
             // }
 
-            if let Some(repo) = &self.repo {
+            if let (Some(repo), Some(root)) = (&self.repo, &self.repo_root) {
+                let state = self.repo_states.entry(root.clone()).or_default();
+
                 ui.vertical_centered_justified(|ui| {
-                    let any_staged = repo.get_statuses().iter().any(|s| {
-                        s.status.is_index_deleted()
-                            || s.status.is_index_modified()
-                            || s.status.is_index_new()
-                            || s.status.is_index_renamed()
-                    });
+                    let staged = repo.get_staged();
+                    let any_staged = !staged.is_empty();
 
                     ui.add_enabled_ui(any_staged, |ui| {
-                        egui::TextEdit::multiline(&mut self.commit_message)
+                        egui::TextEdit::multiline(&mut state.commit_message)
                             .desired_rows(1)
                             .hint_text("Commit message")
                             .desired_width(ui.available_width())
                             .show(ui);
 
-                        ui.add_enabled_ui(!self.commit_message.is_empty(), |ui| {
-                            if ui.button("Commit").clicked() {
-                                match repo.commit() {
-                                    Ok(_) => self.commit_message.clear(),
-                                    Err(e) => {
-                                        self.toasts.error(format!("{e}"));
+                        if any_staged {
+                            section(ui, "Commit preview", &mut state.open_sections, |ui| {
+                                for file in &staged {
+                                    ui.horizontal(|ui| {
+                                        unselected_label(delta_text(file.delta), ui);
+                                        unselected_label(file.path.display().to_string(), ui);
+                                        ui.with_layout(
+                                            egui::Layout::right_to_left(egui::Align::Center),
+                                            |ui| {
+                                                unselected_label(
+                                                    egui::RichText::new(format!(
+                                                        "-{}",
+                                                        file.deletions
+                                                    ))
+                                                    .color(ui.visuals().error_fg_color),
+                                                    ui,
+                                                );
+                                                unselected_label(
+                                                    egui::RichText::new(format!(
+                                                        "+{}",
+                                                        file.insertions
+                                                    ))
+                                                    .color(egui::Color32::GREEN),
+                                                    ui,
+                                                );
+                                            },
+                                        );
+                                    });
+                                }
+                            });
+                        }
+
+                        ui.add_enabled_ui(
+                            !state.commit_message.is_empty() && repo.get_operation().is_none(),
+                            |ui| {
+                                if ui.button("Commit").clicked() {
+                                    match repo.commit() {
+                                        Ok(_) => {
+                                            state.commit_message.clear();
+                                            // The old index can't be restored on top of the new commit
+                                            self.staging_undo = None;
+                                        }
+                                        Err(e) => {
+                                            self.toasts.error(format!("{e}"));
+                                        }
                                     }
                                 }
-                            }
-                        });
+                            },
+                        );
                     });
                 });
 
                 egui::ScrollArea::vertical().show(ui, |ui| {
-                    egui::CollapsingHeader::new("Changes")
-                        .default_open(true)
-                        .show(ui, |ui| {
-                            for (i, status) in repo.get_statuses().iter().enumerate() {
-                                ui.horizontal(|ui| {
-                                    let row_rect = ui.available_rect_before_wrap();
-
-                                    if ui.rect_contains_pointer(row_rect) {
-                                        ui.painter().rect(
-                                            row_rect,
-                                            0.,
-                                            ui.style().visuals.widgets.hovered.bg_fill,
-                                            Stroke::NONE,
-                                            // StrokeKind::Middle,
-                                        );
-                                    }
+                    section(ui, "Changes", &mut state.open_sections, |ui| {
+                        for (i, status) in repo.get_statuses().iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                let row_rect = ui.available_rect_before_wrap();
+                                let is_selected =
+                                    state.selected_file.as_ref() == Some(&status.path);
+                                let is_multi_selected = state.selected_files.contains(&status.path);
 
-                                    if ui.interact(row_rect, Id::new(i), Sense::click()).clicked() {
-                                        info!("Clicked {i}, selected {:?}", self.selected_file);
-                                        if Some(i) == self.selected_file {
-                                            self.selected_file = None;
+                                if ui.rect_contains_pointer(row_rect) {
+                                    ui.painter().rect(
+                                        row_rect,
+                                        0.,
+                                        ui.style().visuals.widgets.hovered.bg_fill,
+                                        Stroke::NONE,
+                                        // StrokeKind::Middle,
+                                    );
+                                }
+
+                                if ui.interact(row_rect, Id::new(i), Sense::click()).clicked() {
+                                    info!("Clicked {i}, selected {:?}", state.selected_file);
+                                    if ui.input(|input| input.modifiers.command) {
+                                        if is_multi_selected {
+                                            state.selected_files.remove(&status.path);
                                         } else {
-                                            self.selected_file = Some(i);
-                                            if let Ok(diff) = repo.diff(&status.path) {
-                                                info!("diff {diff}");
-                                                ui.ctx().data_mut(|w| {
-                                                    w.insert_temp("diff".into(), diff)
-                                                });
-                                            }
+                                            state.selected_files.insert(status.path.clone());
                                         }
-                                    }
-
-                                    if Some(i) == self.selected_file {
-                                        ui.painter().rect(
-                                            row_rect,
-                                            0.,
-                                            ui.style().visuals.widgets.active.bg_fill,
-                                            Stroke::NONE,
-                                            // StrokeKind::Middle,
+                                    } else if is_selected {
+                                        state.selected_file = None;
+                                        state.selected_files.clear();
+                                    } else {
+                                        state.selected_file = Some(status.path.clone());
+                                        state.selected_files =
+                                            BTreeSet::from([status.path.clone()]);
+                                        load_diff(
+                                            ui.ctx(),
+                                            repo,
+                                            state.selected_file.as_ref(),
+                                            state.diff_mode,
                                         );
                                     }
+                                }
 
-                                    unselected_label(
-                                        status
-                                            .path
-                                            .file_name()
-                                            .map(|f| f.to_string_lossy().to_string())
-                                            .unwrap_or_default(),
-                                        ui,
-                                    )
-                                    .on_hover_text(format!("{:?}", status.status));
-
-                                    ui.with_layout(
-                                        egui::Layout::right_to_left(egui::Align::Center),
-                                        |ui| {
-                                            unselected_label(status_text(status.status), ui);
-
-                                            if ui.rect_contains_pointer(row_rect) {
-                                                if status.status.is_index_new()
-                                                    || status.status.is_index_modified()
-                                                {
-                                                    if ui.button(MINUS).clicked() {
-                                                        _ = repo.unstage(&status.path);
-                                                    }
+                                if is_selected || is_multi_selected {
+                                    ui.painter().rect(
+                                        row_rect,
+                                        0.,
+                                        ui.style().visuals.widgets.active.bg_fill,
+                                        Stroke::NONE,
+                                        // StrokeKind::Middle,
+                                    );
+                                }
+
+                                unselected_label(
+                                    status
+                                        .path
+                                        .file_name()
+                                        .map(|f| f.to_string_lossy().to_string())
+                                        .unwrap_or_default(),
+                                    ui,
+                                )
+                                .on_hover_text(format!("{:?}", status.status));
+
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        unselected_label(status_text(status.status), ui);
+
+                                        if ui.rect_contains_pointer(row_rect) {
+                                            if status.status.is_index_new()
+                                                || status.status.is_index_modified()
+                                            {
+                                                if ui.button(MINUS).clicked() {
+                                                    change_staging(
+                                                        repo,
+                                                        &mut self.staging_undo,
+                                                        &mut self.toasts,
+                                                        || repo.unstage(&status.path),
+                                                    );
                                                 }
+                                            }
 
-                                                if status.status.is_wt_new()
-                                                    || status.status.is_wt_modified()
-                                                {
-                                                    if ui.button(PLUS).clicked() {
-                                                        _ = repo.stage(&status.path);
-                                                    }
+                                            if status.status.is_wt_new()
+                                                || status.status.is_wt_modified()
+                                                || status.status.is_conflicted()
+                                            {
+                                                if ui.button(PLUS).clicked() {
+                                                    change_staging(
+                                                        repo,
+                                                        &mut self.staging_undo,
+                                                        &mut self.toasts,
+                                                        || repo.stage(&status.path),
+                                                    );
                                                 }
                                             }
-                                        },
-                                    );
+                                        }
+                                    },
+                                );
 
-                                    ui.end_row();
-                                });
+                                ui.end_row();
+                            });
+                        }
+
+                        if !state.selected_files.is_empty()
+                            && ui
+                                .button(format!(
+                                    "{ARCHIVE} Stash selected ({})",
+                                    state.selected_files.len()
+                                ))
+                                .on_hover_text("Set the selected files aside, keep everything else")
+                                .clicked()
+                        {
+                            let paths = state.selected_files.iter().cloned().collect::<Vec<_>>();
+                            match repo.stash_paths(&paths) {
+                                Ok(_) => {
+                                    self.staging_undo = None;
+                                    state.selected_files.clear();
+                                    state.selected_file = None;
+                                }
+                                Err(e) => {
+                                    self.toasts.error(e.to_string());
+                                }
                             }
-                        });
+                        }
+                    });
+
+                    if state.selected_file.is_some() {
+                        section(ui, "Diff", &mut state.open_sections, |ui| {
+                            ui.horizontal(|ui| {
+                                let previous = state.diff_mode;
+                                ui.selectable_value(
+                                    &mut state.diff_mode,
+                                    DiffMode::HeadToWorkdir,
+                                    "HEAD ↔ Working tree",
+                                );
+                                ui.selectable_value(
+                                    &mut state.diff_mode,
+                                    DiffMode::IndexToWorkdir,
+                                    "Unstaged",
+                                );
+                                ui.selectable_value(
+                                    &mut state.diff_mode,
+                                    DiffMode::HeadToIndex,
+                                    "Staged",
+                                );
+                                if state.diff_mode != previous {
+                                    load_diff(
+                                        ui.ctx(),
+                                        repo,
+                                        state.selected_file.as_ref(),
+                                        state.diff_mode,
+                                    );
+                                }
+                            });
 
-                    if self.selected_file.is_some() {
-                        ui.collapsing("Diff", |ui| {
                             if let Some(diff) =
                                 ui.ctx().data(|r| r.get_temp::<String>("diff".into()))
                             {
@@ -272,7 +806,109 @@ impl eframe::App for GitApp {
                         });
                     }
 
-                    ui.collapsing("Log", |ui| {
+                    section(ui, "Branches", &mut state.open_sections, |ui| {
+                        for branch in repo.get_branches() {
+                            ui.horizontal(|ui| {
+                                let name =
+                                    egui::RichText::new(format!("{GIT_BRANCH} {}", branch.name));
+                                unselected_label(
+                                    if branch.is_head { name.strong() } else { name },
+                                    ui,
+                                );
+
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        let Some(upstream) = &branch.upstream else {
+                                            ui.add_enabled(
+                                                false,
+                                                egui::Label::new("no upstream").selectable(false),
+                                            );
+                                            return;
+                                        };
+
+                                        if branch.behind > 0 {
+                                            unselected_label(
+                                                egui::RichText::new(format!(
+                                                    "{ARROW_DOWN}{}",
+                                                    branch.behind
+                                                ))
+                                                .color(ui.visuals().warn_fg_color),
+                                                ui,
+                                            )
+                                            .on_hover_text(format!(
+                                                "{} commits to pull from {upstream}",
+                                                branch.behind
+                                            ));
+                                        }
+                                        if branch.ahead > 0 {
+                                            unselected_label(
+                                                egui::RichText::new(format!(
+                                                    "{ARROW_UP}{}",
+                                                    branch.ahead
+                                                ))
+                                                .color(ui.visuals().warn_fg_color),
+                                                ui,
+                                            )
+                                            .on_hover_text(format!(
+                                                "{} commits to push to {upstream}",
+                                                branch.ahead
+                                            ));
+                                        }
+                                        if branch.ahead == 0 && branch.behind == 0 {
+                                            unselected_label("up to date", ui)
+                                                .on_hover_text(format!("Same as {upstream}"));
+                                        }
+                                    },
+                                );
+                            });
+                        }
+                    });
+
+                    section(ui, "Stashes", &mut state.open_sections, |ui| {
+                        if ui.button(format!("{ARCHIVE} Stash all changes")).clicked() {
+                            match repo.stash() {
+                                Ok(_) => self.staging_undo = None,
+                                Err(e) => {
+                                    self.toasts.error(e.to_string());
+                                }
+                            }
+                        }
+
+                        for stash in repo.get_stashes() {
+                            ui.horizontal(|ui| {
+                                unselected_label(stash.message, ui).on_hover_text(&stash.commit);
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        if ui.button("Pop").clicked() {
+                                            match repo.stash_pop(stash.index) {
+                                                Ok(_) => self.staging_undo = None,
+                                                Err(e) => {
+                                                    self.toasts.error(e.to_string());
+                                                }
+                                            }
+                                        }
+                                    },
+                                );
+                            });
+                        }
+                    });
+
+                    section(ui, "Log", &mut state.open_sections, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Depth");
+                            if ui
+                                .add(egui::DragValue::new(&mut state.log_depth).range(1..=10000))
+                                .changed()
+                            {
+                                repo.set_log_depth(state.log_depth);
+                                if let Err(e) = repo.refresh() {
+                                    self.toasts.error(e.to_string());
+                                }
+                            }
+                        });
+
                         for logitem in repo.get_log() {
                             ui.horizontal(|ui| {
                                 ui.label("Name");
@@ -281,7 +917,37 @@ impl eframe::App for GitApp {
                                 ui.label(logitem.email);
                             });
                             ui.horizontal(|ui| {
-                                ui.label(logitem.message);
+                                ui.label(&logitem.message);
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        if ui
+                                            .button(format!("{CLOCK_COUNTER_CLOCKWISE} Browse"))
+                                            .on_hover_text("Browse the files as of this commit")
+                                            .clicked()
+                                        {
+                                            match repo.walk_tree(&logitem.commit) {
+                                                Ok(files) => {
+                                                    self.time_travel = Some(TimeTravel {
+                                                        commit: logitem.commit.clone(),
+                                                        summary: logitem
+                                                            .message
+                                                            .lines()
+                                                            .next()
+                                                            .unwrap_or_default()
+                                                            .to_string(),
+                                                        files,
+                                                        selected_file: None,
+                                                        content: String::new(),
+                                                    })
+                                                }
+                                                Err(e) => {
+                                                    self.toasts.error(e.to_string());
+                                                }
+                                            }
+                                        }
+                                    },
+                                );
                             });
                             ui.separator();
                         }
@@ -292,6 +958,51 @@ impl eframe::App for GitApp {
     }
 }
 
+/// Stages or unstages something and, if that worked, keeps the index from before for undo
+fn change_staging(
+    repo: &RepoCache,
+    staging_undo: &mut Option<IndexSnapshot>,
+    toasts: &mut Toasts,
+    change: impl FnOnce() -> Result<()>,
+) {
+    let snapshot = repo.snapshot_index();
+    match change() {
+        Ok(_) => *staging_undo = snapshot.ok(),
+        Err(e) => {
+            toasts.error(e.to_string());
+        }
+    }
+}
+
+/// Load the diff of the selected file into temp storage, where the diff view picks it up.
+fn load_diff(ctx: &egui::Context, repo: &RepoCache, path: Option<&PathBuf>, mode: DiffMode) {
+    let diff = path.and_then(|path| repo.diff_with_mode(path, mode).ok());
+    ctx.data_mut(|w| match diff {
+        Some(diff) => w.insert_temp("diff".into(), diff),
+        None => w.remove::<String>("diff".into()),
+    });
+}
+
+/// A collapsing header that remembers whether it is open in `open_sections`
+fn section(
+    ui: &mut Ui,
+    title: &str,
+    open_sections: &mut BTreeSet<String>,
+    add_contents: impl FnOnce(&mut Ui),
+) {
+    let is_open = open_sections.contains(title);
+    let response = egui::CollapsingHeader::new(title)
+        .open(Some(is_open))
+        .show(ui, add_contents);
+    if response.header_response.clicked() {
+        if is_open {
+            open_sections.remove(title);
+        } else {
+            open_sections.insert(title.to_string());
+        }
+    }
+}
+
 fn open_repo() -> Result<RepoCache> {
     let folder = rfd::FileDialog::new().pick_folder().context("No folder")?;
     info!("Opening: {}", folder.display());
@@ -299,11 +1010,95 @@ fn open_repo() -> Result<RepoCache> {
     Ok(repo)
 }
 
+/// Lets the user pick a folder and makes sure it is a repository
+fn pick_repo() -> Result<PathBuf> {
+    let folder = rfd::FileDialog::new().pick_folder().context("No folder")?;
+    RepoCache::open(&folder)?;
+    Ok(folder)
+}
+
+/// Shows a pre-ordered tree listing as nested folders and returns the file that was clicked.
+fn tree_view(ui: &mut Ui, items: &[TreeItem], selected: Option<&PathBuf>) -> Option<PathBuf> {
+    let mut clicked = None;
+    let mut i = 0;
+    while i < items.len() {
+        let item = &items[i];
+        let name = item
+            .path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if item.is_dir {
+            // The contents of a directory follow it directly
+            let end = items[i + 1..]
+                .iter()
+                .position(|child| !child.path.starts_with(&item.path))
+                .map_or(items.len(), |pos| i + 1 + pos);
+            egui::CollapsingHeader::new(format!("{FOLDER} {name}"))
+                .id_salt(&item.path)
+                .show(ui, |ui| {
+                    if let Some(path) = tree_view(ui, &items[i + 1..end], selected) {
+                        clicked = Some(path);
+                    }
+                });
+            i = end;
+        } else {
+            if ui
+                .selectable_label(selected == Some(&item.path), format!("{FILE} {name}"))
+                .clicked()
+            {
+                clicked = Some(item.path.clone());
+            }
+            i += 1;
+        }
+    }
+    clicked
+}
+
 /// Just a helper for unselected labels
 fn unselected_label(text: impl Into<WidgetText>, ui: &mut Ui) -> Response {
     ui.add(egui::Label::new(text).selectable(false))
 }
 
+/// Describes an operation in progress, e.g. "Rebasing main onto 1a2b3c4 (2/5)"
+fn operation_text(state: &OperationState) -> String {
+    let short = |id: &String| id.chars().take(7).collect::<String>();
+    let heads = state.heads.iter().map(short).collect::<Vec<_>>().join(", ");
+    match state.operation {
+        Operation::Merge => format!("Merging {heads}"),
+        Operation::CherryPick => format!("Cherry-picking {heads}"),
+        Operation::Revert => format!("Reverting {heads}"),
+        Operation::Rebase => {
+            let mut text = format!(
+                "Rebasing {}",
+                state.branch.as_deref().unwrap_or("detached HEAD")
+            );
+            if let Some(onto) = &state.onto {
+                text.push_str(&format!(" onto {}", short(onto)));
+            }
+            if let Some((step, total)) = state.progress {
+                text.push_str(&format!(" ({step}/{total})"));
+            }
+            text
+        }
+        Operation::Bisect => "Bisecting".to_string(),
+        Operation::ApplyMailbox => "Applying patches".to_string(),
+    }
+}
+
+fn delta_text(delta: Delta) -> &'static str {
+    match delta {
+        Delta::Added => "A",
+        Delta::Deleted => "D",
+        Delta::Modified => "M",
+        Delta::Renamed => "R",
+        Delta::Copied => "C",
+        Delta::Typechange => "T",
+        _ => "?",
+    }
+}
+
 fn status_text(status: Status) -> &'static str {
     if status.is_conflicted() {
         return "!";